@@ -1,7 +1,16 @@
 // Some reason the co_class macro uses null comparison
 #![allow(clippy::cmp_null)]
 
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+use std::time::Duration;
+
 use com::{co_class, interfaces::IUnknown, ComRc};
+use windows::core::HSTRING;
+use windows::Win32::Foundation::{CloseHandle, HANDLE};
+use windows::Win32::System::SystemInformation::{RtlGetVersion, OSVERSIONINFOW};
+use windows::Win32::System::Threading::{CreateEventW, SetEvent};
 
 use crate::{
     get_desktops, get_index_by_desktop,
@@ -12,15 +21,149 @@ use crate::{
     },
     DesktopID, HWND,
 };
-use crossbeam_channel::{Receiver, Sender};
+use crossbeam_channel::{Receiver, RecvTimeoutError, Sender};
+
+// First Windows 11 build number; the name/wallpaper/move notifications only
+// ever fire on shells built against this release or later.
+const WINDOWS_11_BUILD_NUMBER: u32 = 22000;
+
+/// Detects whether the running shell is new enough to deliver the extended
+/// (name/wallpaper/move) notification set, so the listener knows whether to
+/// expect them and signal for them.
+///
+/// Uses `RtlGetVersion` rather than `GetVersionExW`: the latter is shimmed by
+/// the application-compatibility layer and lies about the build number to
+/// processes without a matching manifest, which is exactly the kind of
+/// silent misdetection this is trying to avoid.
+fn is_windows_11_or_later() -> bool {
+    let mut info: OSVERSIONINFOW = unsafe { std::mem::zeroed() };
+    info.dwOSVersionInfoSize = std::mem::size_of::<OSVERSIONINFOW>() as u32;
+    if unsafe { RtlGetVersion(&mut info) }.is_ok() {
+        info.dwBuildNumber >= WINDOWS_11_BUILD_NUMBER
+    } else {
+        false
+    }
+}
 
+// Every desktop variant below carries the raw `DesktopID` the notification
+// actually fired for. All of them except `DesktopMoved` pair it with a
+// best-effort index resolved from the current
+// `get_desktops()`/`get_index_by_desktop()` snapshot at notification time.
+// During rapid create/destroy churn that resolution can fail, or the index
+// can already be stale by the time a consumer reads it, so that index is
+// `Option<usize>` and is a convenience only - the `DesktopID` is the
+// authoritative identity and is always present, even when the index isn't.
+// `DesktopMoved` carries the old and new indices as the notification reports
+// them, as plain `usize`. `WindowChanged` carries only the `HWND`, no
+// `DesktopID`.
+#[derive(Clone)]
 pub enum VirtualDesktopEvent {
-    DesktopCreated(usize),
-    DesktopDestroyed(usize),
-    DesktopChanged(usize, usize),
+    DesktopCreated(DesktopID, Option<usize>),
+    DesktopDestroyed(DesktopID, Option<usize>),
+    DesktopDestroyBegin(DesktopID, Option<usize>),
+    DesktopChanged {
+        old_id: DesktopID,
+        old_index: Option<usize>,
+        new_id: DesktopID,
+        new_index: Option<usize>,
+    },
+    DesktopNameChanged(DesktopID, Option<usize>, String),
+    DesktopWallpaperChanged(DesktopID, Option<usize>, String),
+    DesktopMoved {
+        id: DesktopID,
+        old_index: usize,
+        new_index: usize,
+    },
     WindowChanged(HWND),
 }
 
+/// A callback invoked with a reference to a fired [`VirtualDesktopEvent`].
+type EventCallback = Box<dyn FnMut(&VirtualDesktopEvent)>;
+
+#[derive(Default)]
+struct SignalerState {
+    next_id: usize,
+    callbacks: HashMap<usize, EventCallback>,
+    // Ids whose `SignalToken` was dropped while their callback was out of
+    // `callbacks` (i.e. mid-dispatch, see `signal()`). `signal()` consults
+    // this right before it would otherwise reinsert a callback it just ran,
+    // so a genuine self-unsubscribe during dispatch actually takes effect.
+    removed_during_dispatch: HashSet<usize>,
+}
+
+/// Multi-subscriber dispatcher for [`VirtualDesktopEvent`]s, modeled on
+/// Smithay's `Signaler`/`Linkable` pattern: any number of callbacks can be
+/// registered, and all of them run in turn whenever an event fires, instead
+/// of a single consumer fighting everyone else over one channel.
+///
+/// Callbacks run synchronously on the COM notification thread - keep them
+/// short and non-blocking. Use [`RegisteredListener::get_receiver`] if you
+/// need to hand events off to another thread instead.
+#[derive(Clone, Default)]
+pub struct Signaler(Rc<RefCell<SignalerState>>);
+
+impl Signaler {
+    /// Registers `callback` and returns a [`SignalToken`] guard. Dropping the
+    /// token unregisters the callback; other tokens are unaffected.
+    pub fn register(&self, callback: impl FnMut(&VirtualDesktopEvent) + 'static) -> SignalToken {
+        let mut state = self.0.borrow_mut();
+        let id = state.next_id;
+        state.next_id += 1;
+        state.callbacks.insert(id, Box::new(callback));
+        SignalToken {
+            state: self.0.clone(),
+            id,
+        }
+    }
+
+    /// Invokes every currently registered callback with `event`.
+    ///
+    /// Re-entrancy-safe: the ids to invoke are snapshotted before any
+    /// callback runs, and each callback is removed from the map while it
+    /// runs and reinserted afterwards. That means a callback that registers
+    /// or unregisters another callback during dispatch cannot deadlock the
+    /// `RefCell`. A callback registered during dispatch is first invoked by
+    /// the next `signal()` call. A callback unregistered during dispatch is
+    /// not invoked again, including later in the same dispatch; a callback
+    /// unsubscribing itself is covered too: `SignalToken::drop` records the id in
+    /// `removed_during_dispatch` when it can't find it in `callbacks` (because
+    /// it's mid-dispatch), and `signal()` checks that set before reinserting,
+    /// so the callback isn't resurrected by the very dispatch it unsubscribed
+    /// during.
+    pub fn signal(&self, event: &VirtualDesktopEvent) {
+        let ids: Vec<usize> = self.0.borrow().callbacks.keys().copied().collect();
+        for id in ids {
+            let callback = self.0.borrow_mut().callbacks.remove(&id);
+            if let Some(mut callback) = callback {
+                callback(event);
+                let mut state = self.0.borrow_mut();
+                if !state.removed_during_dispatch.remove(&id) {
+                    state.callbacks.insert(id, callback);
+                }
+            }
+        }
+    }
+}
+
+/// Guard returned by [`Signaler::register`]. Removes its callback from the
+/// signaler when dropped.
+pub struct SignalToken {
+    state: Rc<RefCell<SignalerState>>,
+    id: usize,
+}
+
+impl Drop for SignalToken {
+    fn drop(&mut self) {
+        let mut state = self.state.borrow_mut();
+        if state.callbacks.remove(&self.id).is_none() {
+            // Not currently in `callbacks` - we're running inside our own
+            // callback's `signal()` dispatch. Flag it so that dispatch
+            // doesn't reinsert us once the callback returns.
+            state.removed_during_dispatch.insert(self.id);
+        }
+    }
+}
+
 pub struct RegisteredListener {
     // This is the value for registrations and unregistrations
     cookie: u32,
@@ -29,27 +172,79 @@ pub struct RegisteredListener {
     #[allow(dead_code)]
     listener: Box<VirtualDesktopChangeListener>,
 
-    // Receiver
+    // Dispatches every notification to all of this listener's subscribers
+    signaler: Signaler,
+
+    // Keeps the channel-forwarding callback backing `get_receiver()` alive
+    // for as long as this listener is registered.
+    #[allow(dead_code)]
+    legacy_forward: SignalToken,
+
+    // Receiver backing the legacy `get_receiver()` adapter
     receiver: Receiver<VirtualDesktopEvent>,
 
+    // Keeps the channel-forwarding callback backing `get_poll_source()`
+    // alive for as long as this listener is registered.
+    #[allow(dead_code)]
+    poll_forward: SignalToken,
+
+    // Receiver and waker backing the `get_poll_source()` adapter
+    poll_receiver: Receiver<VirtualDesktopEvent>,
+    waker: HANDLE,
+
+    // Keeps the raw-event-forwarding callback feeding the coalescing timer
+    // thread alive for as long as this listener is registered, when
+    // coalescing is enabled.
+    #[allow(dead_code)]
+    coalesce_forward: Option<SignalToken>,
+
+    // Receiver for deduplicated batches, when coalescing is enabled
+    coalesced_receiver: Option<Receiver<VirtualDesktopEvent>>,
+
     // Unregistration on drop requires a notification service
     service: ComRc<dyn IVirtualDesktopNotificationService>,
 }
-unsafe impl Send for RegisteredListener {}
-unsafe impl Sync for RegisteredListener {}
+
+// No manual Send/Sync here: `signaler` (and the tokens derived from it) are
+// backed by `Rc<RefCell<_>>`, whose refcount and borrow-flag updates aren't
+// synchronized across threads. Claiming `Sync` on top of that would let two
+// threads call `on_event`/drop a `SignalToken` concurrently and race those
+// updates - real UB, not just a possible panic. If cross-thread use is ever
+// needed, `Signaler` would need to move to `Arc<Mutex<_>>` first; until then
+// this type is thread-confined like the `Rc` it carries.
 
 impl RegisteredListener {
+    /// Registers for desktop notifications. When `coalesce` is `Some`, an
+    /// extra channel is set up whose events are not forwarded as they arrive;
+    /// instead they are merged into a buffer (see [`coalesce_into`]) and
+    /// flushed as a deduplicated batch once `coalesce` has passed with no
+    /// further notifications, so e.g. a burst of `WindowChanged` during a
+    /// desktop switch doesn't redraw a UI consumer dozens of times. Read the
+    /// batch with [`RegisteredListener::get_coalesced_receiver`]. The
+    /// channels behind `get_receiver` and `get_poll_source` are not
+    /// coalesced: they still get every raw notification as it arrives.
     pub fn register(
-        sender: Sender<VirtualDesktopEvent>,
-        receiver: Receiver<VirtualDesktopEvent>,
         service: ComRc<dyn IVirtualDesktopNotificationService>,
+        coalesce: Option<Duration>,
     ) -> Result<RegisteredListener, HRESULT> {
-        let listener = VirtualDesktopChangeListener::create(sender);
+        let signaler = Signaler::default();
+        let extended = is_windows_11_or_later();
+        let listener = VirtualDesktopChangeListener::create(signaler.clone(), extended);
         let ptr: ComRc<dyn IVirtualDesktopNotification> = unsafe {
             ComRc::from_raw(&listener.__ivirtualdesktopnotificationvptr as *const _ as *mut _)
         };
 
-        // Register the IVirtualDesktopNotification to the service
+        // Register the IVirtualDesktopNotification to the service. There is
+        // only one `IVirtualDesktopNotification` vtable in `crate::interfaces`
+        // - `listener.extended` only controls whether *this* listener's own
+        // method bodies signal the name/wallpaper/move events, it isn't a
+        // second interface COM could reject independently of the first. So
+        // unlike a real extended/base interface pair, there's nothing
+        // meaningful to retry with here if `register` fails: the shell either
+        // accepts `IVirtualDesktopNotification` or it doesn't, regardless of
+        // `extended`. If `crate::interfaces` ever grows a distinct base-only
+        // notification interface, that's what a retry should advertise
+        // instead of the same `ptr`.
         let mut cookie = 0;
         let res = unsafe { service.register(ptr.clone(), &mut cookie) };
         if res.failed() {
@@ -66,18 +261,96 @@ impl RegisteredListener {
                 std::thread::current().id()
             );
 
+            let (sender, receiver) = crossbeam_channel::unbounded();
+            let legacy_forward = signaler.register(move |event| {
+                let _ = sender.try_send(event.clone());
+            });
+
+            // Auto-reset: a single `WaitForMultipleObjects` wakeup always
+            // corresponds to at least one queued event, and the caller is
+            // expected to drain `poll_receiver` fully on each wakeup anyway.
+            let waker = match unsafe { CreateEventW(None, false, false, None) } {
+                Ok(waker) => waker,
+                Err(e) => {
+                    // We already registered with the service above - undo
+                    // that before bailing out so we don't leak the
+                    // registration on this runtime failure.
+                    unsafe {
+                        service.unregister(cookie);
+                    }
+                    return Err(HRESULT(e.code().0));
+                }
+            };
+            let (poll_sender, poll_receiver) = crossbeam_channel::unbounded();
+            let poll_forward = signaler.register(move |event| {
+                let _ = poll_sender.try_send(event.clone());
+                unsafe {
+                    let _ = SetEvent(waker);
+                }
+            });
+
+            let (coalesce_forward, coalesced_receiver) = match coalesce {
+                Some(quiet_period) => {
+                    let (raw_sender, raw_receiver) = crossbeam_channel::unbounded();
+                    let (out_sender, out_receiver) = crossbeam_channel::unbounded();
+                    // The COM callback only enqueues here; the timer thread
+                    // owns the buffer and does all the coalescing work.
+                    let forward = signaler.register(move |event| {
+                        let _ = raw_sender.try_send(event.clone());
+                    });
+                    spawn_coalescing_thread(raw_receiver, out_sender, quiet_period);
+                    (Some(forward), Some(out_receiver))
+                }
+                None => (None, None),
+            };
+
             Ok(RegisteredListener {
                 cookie,
                 listener,
+                signaler,
+                legacy_forward,
                 receiver,
+                poll_forward,
+                poll_receiver,
+                waker,
+                coalesce_forward,
+                coalesced_receiver,
                 service: service.clone(),
             })
         }
     }
 
+    /// Subscribes `callback` to every [`VirtualDesktopEvent`] this listener
+    /// observes. Multiple independent subscribers can be registered at once
+    /// (e.g. a tray updater and a hotkey handler), each with its own
+    /// [`SignalToken`] that unsubscribes on drop.
+    pub fn on_event(&self, callback: impl FnMut(&VirtualDesktopEvent) + 'static) -> SignalToken {
+        self.signaler.register(callback)
+    }
+
+    /// Backward-compatible channel adapter: `register` adds one callback on
+    /// the [`Signaler`] that forwards every event into a crossbeam channel,
+    /// and this returns a clone of that channel's receiver.
     pub fn get_receiver(&self) -> Receiver<VirtualDesktopEvent> {
         self.receiver.clone()
     }
+
+    /// Returns a [`PollableEvents`] source for integrating with a caller's
+    /// own event loop instead of a dedicated channel-draining thread: wait on
+    /// `waker` (e.g. with `WaitForMultipleObjects`, alongside your own
+    /// message pump) and drain `receiver` whenever it fires.
+    pub fn get_poll_source(&self) -> PollableEvents {
+        PollableEvents {
+            receiver: self.poll_receiver.clone(),
+            waker: self.waker,
+        }
+    }
+
+    /// Returns the receiver for deduplicated batches when this listener was
+    /// registered with `coalesce: Some(_)`, or `None` otherwise.
+    pub fn get_coalesced_receiver(&self) -> Option<Receiver<VirtualDesktopEvent>> {
+        self.coalesced_receiver.clone()
+    }
 }
 
 impl Drop for RegisteredListener {
@@ -86,13 +359,35 @@ impl Drop for RegisteredListener {
         println!("Unregister a listener {:?}", self.cookie);
         unsafe {
             self.service.unregister(self.cookie);
+            // The poll waker is created alongside registration above, so it
+            // is closed alongside unregistration here.
+            let _ = CloseHandle(self.waker);
         }
     }
 }
 
+/// A [`VirtualDesktopEvent`] source for integrating with an external event
+/// loop, as returned by [`RegisteredListener::get_poll_source`].
+///
+/// `waker` is a Win32 auto-reset event signaled from inside the COM
+/// notification callbacks right after an event is pushed into `receiver`.
+/// Wait on it together with your own message pump (e.g. via
+/// `WaitForMultipleObjects`) and drain `receiver` whenever it's signaled.
+/// The handle is created when the listener is registered and is closed by
+/// [`RegisteredListener`]'s `Drop`, so it stays valid for exactly as long as
+/// the listener it came from.
+pub struct PollableEvents {
+    pub receiver: Receiver<VirtualDesktopEvent>,
+    pub waker: HANDLE,
+}
+
 #[co_class(implements(IVirtualDesktopNotification))]
 struct VirtualDesktopChangeListener {
-    sender: Sender<VirtualDesktopEvent>,
+    signaler: Signaler,
+    // Whether the name/wallpaper/move notifications should be signaled.
+    // Set once from `is_windows_11_or_later()` when the listener is created;
+    // nothing changes it afterwards (`register()` does not retry).
+    extended: Cell<bool>,
 }
 
 impl VirtualDesktopChangeListener {
@@ -103,8 +398,8 @@ impl VirtualDesktopChangeListener {
         // VirtualDesktopChangeListener::allocate()
     }
 
-    fn create(sender: Sender<VirtualDesktopEvent>) -> Box<VirtualDesktopChangeListener> {
-        let v = VirtualDesktopChangeListener::allocate(sender);
+    fn create(signaler: Signaler, extended: bool) -> Box<VirtualDesktopChangeListener> {
+        let v = VirtualDesktopChangeListener::allocate(signaler, Cell::new(extended));
         unsafe {
             v.add_ref();
         }
@@ -122,25 +417,32 @@ impl Drop for VirtualDesktopChangeListener {
     }
 }
 
+// The name/wallpaper/move methods below are only ever invoked by Windows 11
+// shells. `self.extended` is set from `is_windows_11_or_later()` when the
+// listener is created, and each of those three methods checks it before
+// signaling, so a misdetected build can't surface events it never promised.
 impl IVirtualDesktopNotification for VirtualDesktopChangeListener {
     /// On desktop creation
     unsafe fn virtual_desktop_created(&self, desktop: ComRc<dyn IVirtualDesktop>) -> HRESULT {
         let mut id: DesktopID = Default::default();
         desktop.get_id(&mut id);
-        if let Ok(index) = get_index_by_desktop(id) {
-            let _ = self
-                .sender
-                .try_send(VirtualDesktopEvent::DesktopCreated(index));
-        }
+        let index = get_index_by_desktop(id).ok();
+        self.signaler
+            .signal(&VirtualDesktopEvent::DesktopCreated(id, index));
         HRESULT::ok()
     }
 
     /// On desktop destroy begin
     unsafe fn virtual_desktop_destroy_begin(
         &self,
-        _destroyed_desktop: ComRc<dyn IVirtualDesktop>,
+        destroyed_desktop: ComRc<dyn IVirtualDesktop>,
         _fallback_desktop: ComRc<dyn IVirtualDesktop>,
     ) -> HRESULT {
+        let mut id: DesktopID = Default::default();
+        destroyed_desktop.get_id(&mut id);
+        let index = get_index_by_desktop(id).ok();
+        self.signaler
+            .signal(&VirtualDesktopEvent::DesktopDestroyBegin(id, index));
         HRESULT::ok()
     }
 
@@ -162,12 +464,12 @@ impl IVirtualDesktopNotification for VirtualDesktopChangeListener {
         let mut id: DesktopID = Default::default();
         destroyed_desktop.get_id(&mut id);
 
-        // TODO: Can this work, should I move this to destroy begin?
-        if let Ok(index) = get_index_by_desktop(id) {
-            let _ = self
-                .sender
-                .try_send(VirtualDesktopEvent::DesktopDestroyed(index));
-        }
+        // The index lookup can fail once the desktop is gone - that no
+        // longer drops the event, since `id` alone is enough for a consumer
+        // to know which desktop was destroyed.
+        let index = get_index_by_desktop(id).ok();
+        self.signaler
+            .signal(&VirtualDesktopEvent::DesktopDestroyed(id, index));
         HRESULT::ok()
     }
 
@@ -183,9 +485,8 @@ impl IVirtualDesktopNotification for VirtualDesktopChangeListener {
             std::thread::current().id()
         );
 
-        let _ = self
-            .sender
-            .try_send(VirtualDesktopEvent::WindowChanged(hwnd));
+        self.signaler
+            .signal(&VirtualDesktopEvent::WindowChanged(hwnd));
 
         HRESULT::ok()
     }
@@ -204,23 +505,274 @@ impl IVirtualDesktopNotification for VirtualDesktopChangeListener {
         #[cfg(feature = "debug")]
         println!("-> Desktop changed {:?}", std::thread::current().id());
 
-        // Get desktop indices and notify back
+        // Resolve best-effort indices from the current snapshot, but signal
+        // regardless of whether either side resolves - `old_id`/`new_id`
+        // alone are enough to identify which desktops changed.
+        let mut old_index = None;
+        let mut new_index = None;
         if let Ok(desktops) = get_desktops() {
-            let mut old = std::usize::MAX;
-            let mut new = std::usize::MAX;
             for (i, desktop) in desktops.iter().enumerate() {
                 if desktop == &old_id {
-                    old = i;
+                    old_index = Some(i);
                 } else if desktop == &new_id {
-                    new = i;
+                    new_index = Some(i);
                 }
             }
-            if old != std::usize::MAX && new != std::usize::MAX {
-                let _ = self
-                    .sender
-                    .try_send(VirtualDesktopEvent::DesktopChanged(old, new));
-            }
         }
+        self.signaler.signal(&VirtualDesktopEvent::DesktopChanged {
+            old_id,
+            old_index,
+            new_id,
+            new_index,
+        });
         HRESULT::ok()
     }
+
+    /// On desktop rename (Windows 11)
+    unsafe fn virtual_desktop_name_changed(
+        &self,
+        desktop: ComRc<dyn IVirtualDesktop>,
+        name: HSTRING,
+    ) -> HRESULT {
+        if !self.extended.get() {
+            return HRESULT::ok();
+        }
+        let mut id: DesktopID = Default::default();
+        desktop.get_id(&mut id);
+        let index = get_index_by_desktop(id).ok();
+        self.signaler
+            .signal(&VirtualDesktopEvent::DesktopNameChanged(
+                id,
+                index,
+                name.to_string(),
+            ));
+        HRESULT::ok()
+    }
+
+    /// On desktop wallpaper change (Windows 11)
+    unsafe fn virtual_desktop_wallpaper_changed(
+        &self,
+        desktop: ComRc<dyn IVirtualDesktop>,
+        name: HSTRING,
+    ) -> HRESULT {
+        if !self.extended.get() {
+            return HRESULT::ok();
+        }
+        let mut id: DesktopID = Default::default();
+        desktop.get_id(&mut id);
+        let index = get_index_by_desktop(id).ok();
+        self.signaler
+            .signal(&VirtualDesktopEvent::DesktopWallpaperChanged(
+                id,
+                index,
+                name.to_string(),
+            ));
+        HRESULT::ok()
+    }
+
+    /// On desktop reorder (Windows 11)
+    unsafe fn virtual_desktop_moved(
+        &self,
+        desktop: ComRc<dyn IVirtualDesktop>,
+        old_index: i64,
+        new_index: i64,
+    ) -> HRESULT {
+        if !self.extended.get() {
+            return HRESULT::ok();
+        }
+        let mut id: DesktopID = Default::default();
+        desktop.get_id(&mut id);
+        self.signaler.signal(&VirtualDesktopEvent::DesktopMoved {
+            id,
+            old_index: old_index as usize,
+            new_index: new_index as usize,
+        });
+        HRESULT::ok()
+    }
+}
+
+/// Drains `raw_receiver`, coalescing bursts of events into `buffer` via
+/// [`coalesce_into`], and flushes the buffer into `out_sender` once
+/// `quiet_period` passes with no new raw event. Owns both the buffer and the
+/// downstream sender, so the COM callback feeding `raw_receiver` only ever
+/// has to enqueue and return.
+///
+/// Exits once `raw_receiver` disconnects, i.e. once the listener that owns
+/// the forwarding callback is dropped. `recv_timeout` reports the disconnect
+/// as soon as it happens; events still in the buffer at that point are
+/// dropped, not flushed.
+fn spawn_coalescing_thread(
+    raw_receiver: Receiver<VirtualDesktopEvent>,
+    out_sender: Sender<VirtualDesktopEvent>,
+    quiet_period: Duration,
+) {
+    std::thread::spawn(move || {
+        let mut buffer: Vec<VirtualDesktopEvent> = Vec::new();
+        loop {
+            match raw_receiver.recv_timeout(quiet_period) {
+                Ok(event) => coalesce_into(&mut buffer, event),
+                Err(RecvTimeoutError::Timeout) => {
+                    for event in buffer.drain(..) {
+                        let _ = out_sender.try_send(event);
+                    }
+                }
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+    });
+}
+
+/// Merges `event` into `buffer`: consecutive `WindowChanged` events for the
+/// same `HWND` collapse into the latest one, and a `DesktopChanged` event
+/// supersedes any earlier one still waiting in the buffer. Every other
+/// variant is just appended, since bursts of those aren't the problem this
+/// is solving.
+fn coalesce_into(buffer: &mut Vec<VirtualDesktopEvent>, event: VirtualDesktopEvent) {
+    match &event {
+        VirtualDesktopEvent::WindowChanged(hwnd) => {
+            // Only pop a *trailing* run of matching `WindowChanged`s, not
+            // every earlier occurrence - an intervening event (e.g. a
+            // `DesktopChanged`) that this one originally followed must stay
+            // behind it, not get jumped ahead of.
+            while matches!(buffer.last(), Some(VirtualDesktopEvent::WindowChanged(h)) if h == hwnd)
+            {
+                buffer.pop();
+            }
+        }
+        VirtualDesktopEvent::DesktopChanged { .. } => {
+            buffer.retain(|e| !matches!(e, VirtualDesktopEvent::DesktopChanged { .. }));
+        }
+        _ => {}
+    }
+    buffer.push(event);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn desktop_changed(old_index: usize, new_index: usize) -> VirtualDesktopEvent {
+        VirtualDesktopEvent::DesktopChanged {
+            old_id: DesktopID::default(),
+            old_index: Some(old_index),
+            new_id: DesktopID::default(),
+            new_index: Some(new_index),
+        }
+    }
+
+    #[test]
+    fn coalesce_collapses_consecutive_window_changed_for_same_hwnd() {
+        let mut buffer = Vec::new();
+        coalesce_into(&mut buffer, VirtualDesktopEvent::WindowChanged(1 as HWND));
+        coalesce_into(&mut buffer, VirtualDesktopEvent::WindowChanged(1 as HWND));
+        coalesce_into(&mut buffer, VirtualDesktopEvent::WindowChanged(1 as HWND));
+
+        assert_eq!(buffer.len(), 1);
+        assert!(matches!(buffer[0], VirtualDesktopEvent::WindowChanged(h) if h == 1 as HWND));
+    }
+
+    #[test]
+    fn coalesce_does_not_reorder_window_changed_past_an_intervening_event() {
+        let mut buffer = Vec::new();
+        coalesce_into(&mut buffer, VirtualDesktopEvent::WindowChanged(1 as HWND));
+        coalesce_into(&mut buffer, desktop_changed(0, 1));
+        coalesce_into(&mut buffer, VirtualDesktopEvent::WindowChanged(1 as HWND));
+
+        assert_eq!(buffer.len(), 3);
+        assert!(matches!(buffer[0], VirtualDesktopEvent::WindowChanged(h) if h == 1 as HWND));
+        assert!(matches!(
+            buffer[1],
+            VirtualDesktopEvent::DesktopChanged { .. }
+        ));
+        assert!(matches!(buffer[2], VirtualDesktopEvent::WindowChanged(h) if h == 1 as HWND));
+    }
+
+    #[test]
+    fn coalesce_supersedes_earlier_desktop_changed() {
+        let mut buffer = Vec::new();
+        coalesce_into(&mut buffer, desktop_changed(0, 1));
+        coalesce_into(&mut buffer, desktop_changed(1, 2));
+
+        assert_eq!(buffer.len(), 1);
+        match &buffer[0] {
+            VirtualDesktopEvent::DesktopChanged {
+                old_index,
+                new_index,
+                ..
+            } => {
+                assert_eq!(*old_index, Some(1));
+                assert_eq!(*new_index, Some(2));
+            }
+            _ => panic!("expected DesktopChanged"),
+        }
+    }
+
+    #[test]
+    fn coalesce_keeps_unrelated_events_in_order() {
+        let mut buffer = Vec::new();
+        coalesce_into(
+            &mut buffer,
+            VirtualDesktopEvent::DesktopCreated(DesktopID::default(), Some(0)),
+        );
+        coalesce_into(
+            &mut buffer,
+            VirtualDesktopEvent::DesktopDestroyed(DesktopID::default(), Some(1)),
+        );
+
+        assert_eq!(buffer.len(), 2);
+        assert!(matches!(buffer[0], VirtualDesktopEvent::DesktopCreated(_, Some(0))));
+        assert!(matches!(buffer[1], VirtualDesktopEvent::DesktopDestroyed(_, Some(1))));
+    }
+
+    #[test]
+    fn signaler_dispatches_to_all_registered_callbacks() {
+        let signaler = Signaler::default();
+        let count_a = Rc::new(RefCell::new(0));
+        let count_b = Rc::new(RefCell::new(0));
+
+        let a = count_a.clone();
+        let _token_a = signaler.register(move |_| *a.borrow_mut() += 1);
+        let b = count_b.clone();
+        let _token_b = signaler.register(move |_| *b.borrow_mut() += 1);
+
+        signaler.signal(&VirtualDesktopEvent::WindowChanged(1 as HWND));
+
+        assert_eq!(*count_a.borrow(), 1);
+        assert_eq!(*count_b.borrow(), 1);
+    }
+
+    #[test]
+    fn dropping_a_signal_token_unsubscribes_it() {
+        let signaler = Signaler::default();
+        let count = Rc::new(RefCell::new(0));
+        let c = count.clone();
+        let token = signaler.register(move |_| *c.borrow_mut() += 1);
+
+        signaler.signal(&VirtualDesktopEvent::WindowChanged(1 as HWND));
+        drop(token);
+        signaler.signal(&VirtualDesktopEvent::WindowChanged(1 as HWND));
+
+        assert_eq!(*count.borrow(), 1);
+    }
+
+    #[test]
+    fn callback_can_unregister_itself_during_dispatch() {
+        let signaler = Signaler::default();
+        let count = Rc::new(RefCell::new(0));
+        let token: Rc<RefCell<Option<SignalToken>>> = Rc::new(RefCell::new(None));
+
+        let c = count.clone();
+        let token_for_callback = token.clone();
+        let t = signaler.register(move |_| {
+            *c.borrow_mut() += 1;
+            // Unsubscribe itself on the very first invocation.
+            token_for_callback.borrow_mut().take();
+        });
+        *token.borrow_mut() = Some(t);
+
+        signaler.signal(&VirtualDesktopEvent::WindowChanged(1 as HWND));
+        signaler.signal(&VirtualDesktopEvent::WindowChanged(1 as HWND));
+
+        assert_eq!(*count.borrow(), 1);
+    }
 }